@@ -0,0 +1,84 @@
+//! SSRI-style (`"{algo}-{base64(digest)}"`) integrity hashing for data as it
+//! streams from `source` to `target`.
+
+use sha2::{Digest, Sha256, Sha512};
+use std::io;
+
+/// Hash algorithm selectable via `--checksum`/`--verify`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChecksumAlgo {
+    Sha256,
+    Sha512,
+    Xxh3,
+}
+
+impl ChecksumAlgo {
+    pub fn parse(name: &str) -> Result<Self, io::Error> {
+        match name {
+            "sha256" => Ok(ChecksumAlgo::Sha256),
+            "sha512" => Ok(ChecksumAlgo::Sha512),
+            "xxh3" => Ok(ChecksumAlgo::Xxh3),
+            other => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("unknown checksum algorithm '{}'", other),
+            )),
+        }
+    }
+}
+
+/// Rolling hasher fed one block at a time as each block is written, so the
+/// digest is ready the instant the copy finishes with no second pass over
+/// the data.
+pub enum RollingHasher {
+    Sha256(Sha256),
+    Sha512(Sha512),
+    Xxh3(xxhash_rust::xxh3::Xxh3),
+}
+
+impl RollingHasher {
+    pub fn new(algo: ChecksumAlgo) -> Self {
+        match algo {
+            ChecksumAlgo::Sha256 => RollingHasher::Sha256(Sha256::new()),
+            ChecksumAlgo::Sha512 => RollingHasher::Sha512(Sha512::new()),
+            ChecksumAlgo::Xxh3 => RollingHasher::Xxh3(xxhash_rust::xxh3::Xxh3::new()),
+        }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        match self {
+            RollingHasher::Sha256(h) => h.update(data),
+            RollingHasher::Sha512(h) => h.update(data),
+            RollingHasher::Xxh3(h) => h.update(data),
+        }
+    }
+
+    /// Finalize into an SSRI-style `"{algo}-{base64(digest)}"` string.
+    pub fn finish(self) -> String {
+        match self {
+            RollingHasher::Sha256(h) => format_digest("sha256", &h.finalize()),
+            RollingHasher::Sha512(h) => format_digest("sha512", &h.finalize()),
+            RollingHasher::Xxh3(h) => format_digest("xxh3", &h.digest128().to_be_bytes()),
+        }
+    }
+}
+
+fn format_digest(algo: &str, digest: &[u8]) -> String {
+    format!("{}-{}", algo, base64::encode(digest))
+}
+
+/// Parse a `--verify` integrity string (`"{algo}-{base64}"`, split on the
+/// first `-`) into the algorithm to hash with and the full string the
+/// computed digest must match.
+pub fn parse_integrity(s: &str) -> Result<(ChecksumAlgo, String), io::Error> {
+    let (algo_name, _digest) = s.split_once('-').ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "malformed integrity string '{}', expected '<algo>-<base64>'",
+                s
+            ),
+        )
+    })?;
+    let algo = ChecksumAlgo::parse(algo_name)?;
+    Ok((algo, s.to_string()))
+}
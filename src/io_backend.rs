@@ -0,0 +1,15 @@
+//! A single `File` type used by [`writer::writer_thread`][crate::writer],
+//! backed by async-std I/O by default or, on Linux with
+//! `--features io-uring`, by a ring-backed implementation. Both expose the
+//! same `open`/`create`/`read`/`write`/`sync_data` surface so the copy loop
+//! doesn't need to know which one it's talking to.
+
+#[cfg(not(feature = "io-uring"))]
+mod default_backend;
+#[cfg(not(feature = "io-uring"))]
+pub use default_backend::File;
+
+#[cfg(feature = "io-uring")]
+mod ring_backend;
+#[cfg(feature = "io-uring")]
+pub use ring_backend::File;
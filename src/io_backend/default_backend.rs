@@ -0,0 +1,83 @@
+use async_std::io::prelude::*;
+use async_std::io::SeekFrom;
+use std::io;
+
+/// Plain async-std-backed file, used everywhere except the optional
+/// `io-uring` build. `"-"` maps to stdin (for `open`) or stdout (for
+/// `create`) so `rr` can sit in a pipeline.
+pub struct File(Inner);
+
+enum Inner {
+    File(async_std::fs::File),
+    Stdin(async_std::io::Stdin),
+    Stdout(async_std::io::Stdout),
+}
+
+impl File {
+    pub async fn open(path: &str) -> io::Result<Self> {
+        if path == "-" {
+            Ok(File(Inner::Stdin(async_std::io::stdin())))
+        } else {
+            Ok(File(Inner::File(async_std::fs::File::open(path).await?)))
+        }
+    }
+
+    /// `truncate` mirrors dd's default of truncating the output file to
+    /// whatever ends up written (like `conv=notrunc`'s absence) -- it's
+    /// turned off when seeking into the target, since a sub-range write
+    /// shouldn't discard whatever comes after it.
+    pub async fn create(path: &str, truncate: bool) -> io::Result<Self> {
+        if path == "-" {
+            Ok(File(Inner::Stdout(async_std::io::stdout())))
+        } else {
+            Ok(File(Inner::File(
+                async_std::fs::OpenOptions::new()
+                    .write(true)
+                    .create(true)
+                    .truncate(truncate)
+                    .open(path)
+                    .await?,
+            )))
+        }
+    }
+
+    pub async fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match &mut self.0 {
+            Inner::File(f) => f.read(buf).await,
+            Inner::Stdin(s) => s.read(buf).await,
+            Inner::Stdout(_) => unreachable!("output handles are never read from"),
+        }
+    }
+
+    pub async fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match &mut self.0 {
+            Inner::File(f) => f.write(buf).await,
+            Inner::Stdout(s) => s.write(buf).await,
+            Inner::Stdin(_) => unreachable!("input handles are never written to"),
+        }
+    }
+
+    pub async fn sync_data(&self) -> io::Result<()> {
+        match &self.0 {
+            Inner::File(f) => f.sync_data().await,
+            Inner::Stdin(_) | Inner::Stdout(_) => Ok(()),
+        }
+    }
+
+    /// Seek to an absolute byte offset. A no-op `pos` of 0 is allowed on a
+    /// pipe; anything else on stdin/stdout is an error since pipes aren't
+    /// seekable.
+    pub async fn seek(&mut self, pos: u64) -> io::Result<()> {
+        match &mut self.0 {
+            Inner::File(f) => {
+                f.seek(SeekFrom::Start(pos)).await?;
+                Ok(())
+            }
+            Inner::Stdin(_) | Inner::Stdout(_) if pos == 0 => Ok(()),
+            Inner::Stdin(_) | Inner::Stdout(_) => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "cannot skip/seek on a pipe",
+            )),
+        }
+    }
+}
@@ -0,0 +1,180 @@
+use io_uring::{opcode, types, IoUring};
+use std::fs::OpenOptions;
+use std::io;
+use std::io::{Seek, SeekFrom};
+use std::os::unix::io::AsRawFd;
+
+/// Depth of the shared submission/completion ring. Small and fixed rather
+/// than configurable, since it just bounds how many ops the kernel is
+/// allowed to have queued for this file at once.
+const RING_DEPTH: u32 = 8;
+
+/// io_uring-backed file. Every op carries an explicit file offset tracked
+/// by `cursor` (io_uring does not auto-advance the file position the way a
+/// plain `read`/`write` syscall does), which `cursor` is advanced by after
+/// each completed op. Reads are pipelined one block ahead of the caller: as
+/// soon as a read completes, the next one is submitted to the ring
+/// immediately so the kernel is already servicing it while the block just
+/// returned gets hashed and written, instead of sitting idle between calls.
+/// Writes are submitted and waited on per call, since their payload isn't
+/// known until the caller hands it over.
+pub struct File {
+    fd: std::fs::File,
+    ring: IoUring,
+    cursor: u64,
+    /// A read already submitted for the offset `cursor` will land at once
+    /// consumed: (offset it targets, destination buffer, requested length).
+    prefetch: Option<(u64, Vec<u8>, usize)>,
+}
+
+impl File {
+    pub async fn open(path: &str) -> io::Result<Self> {
+        let fd = std::fs::File::open(path)?;
+        let ring = IoUring::new(RING_DEPTH)?;
+        Ok(File {
+            fd,
+            ring,
+            cursor: 0,
+            prefetch: None,
+        })
+    }
+
+    pub async fn create(path: &str, truncate: bool) -> io::Result<Self> {
+        let fd = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(truncate)
+            .open(path)?;
+        let ring = IoUring::new(RING_DEPTH)?;
+        Ok(File {
+            fd,
+            ring,
+            cursor: 0,
+            prefetch: None,
+        })
+    }
+
+    pub async fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = match self.prefetch.take() {
+            Some((offset, pending, requested_len)) if offset == self.cursor && requested_len == buf.len() => {
+                let n = self.wait_outstanding()?;
+                buf[..n].copy_from_slice(&pending[..n]);
+                n
+            }
+            Some(_) => {
+                // The caller asked for a different slice than the one we
+                // guessed at (typically the tail of a short read near
+                // EOF). Drain the now-unwanted completion so it doesn't
+                // wedge the ring, then fall back to a synchronous read.
+                let _ = self.wait_outstanding();
+                self.sync_read(buf)?
+            }
+            None => self.sync_read(buf)?,
+        };
+        self.cursor += n as u64;
+        if n > 0 {
+            // A 0-length read means EOF, so there is no next block to
+            // fetch; arming another speculative read here would only leave
+            // it outstanding (and its buffer referenced by the kernel)
+            // until a later read() or drop waits it out.
+            self.start_prefetch(buf.len());
+        }
+        Ok(n)
+    }
+
+    pub async fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let fd = types::Fd(self.fd.as_raw_fd());
+        let entry = opcode::Write::new(fd, buf.as_ptr(), buf.len() as u32)
+            .offset(self.cursor)
+            .build();
+        let n = self.submit(entry)?;
+        self.cursor += n as u64;
+        Ok(n)
+    }
+
+    pub async fn sync_data(&self) -> io::Result<()> {
+        self.fd.sync_data()
+    }
+
+    pub async fn seek(&mut self, pos: u64) -> io::Result<()> {
+        self.fd.seek(SeekFrom::Start(pos))?;
+        if self.prefetch.take().is_some() {
+            // Drop whatever the stale prefetch would have returned; we're
+            // about to read from a different offset entirely.
+            let _ = self.wait_outstanding();
+        }
+        self.cursor = pos;
+        Ok(())
+    }
+
+    fn sync_read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let fd = types::Fd(self.fd.as_raw_fd());
+        let entry = opcode::Read::new(fd, buf.as_mut_ptr(), buf.len() as u32)
+            .offset(self.cursor)
+            .build();
+        self.submit(entry)
+    }
+
+    /// Submit a read for the block starting at the (now up to date)
+    /// `cursor`, without waiting for it, so it's already in flight by the
+    /// time the caller comes back for it.
+    fn start_prefetch(&mut self, len: usize) {
+        if self.prefetch.is_some() || len == 0 {
+            return;
+        }
+
+        let mut buf = vec![0u8; len];
+        let fd = types::Fd(self.fd.as_raw_fd());
+        let entry = opcode::Read::new(fd, buf.as_mut_ptr(), len as u32)
+            .offset(self.cursor)
+            .build();
+
+        let pushed = unsafe { self.ring.submission().push(&entry).is_ok() };
+        if !pushed || self.ring.submit().is_err() {
+            // Ring momentarily full or submission failed; just skip the
+            // prefetch, the next read() will fall back to a sync read.
+            return;
+        }
+
+        self.prefetch = Some((self.cursor, buf, len));
+    }
+
+    /// Block until the single op already sitting in the ring (pushed by
+    /// `submit` or `start_prefetch`) completes.
+    fn wait_outstanding(&mut self) -> io::Result<usize> {
+        self.ring.submit_and_wait(1)?;
+        let cqe = self
+            .ring
+            .completion()
+            .next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::Other, "io_uring completion missing"))?;
+
+        let res = cqe.result();
+        if res < 0 {
+            return Err(io::Error::from_raw_os_error(-res));
+        }
+        Ok(res as usize)
+    }
+
+    /// Push one SQE and block until it completes.
+    fn submit(&mut self, entry: io_uring::squeue::Entry) -> io::Result<usize> {
+        unsafe {
+            self.ring.submission().push(&entry).map_err(|_| {
+                io::Error::new(io::ErrorKind::Other, "io_uring submission queue is full")
+            })?;
+        }
+        self.wait_outstanding()
+    }
+}
+
+impl Drop for File {
+    /// `prefetch`'s buffer must not be freed while the kernel still holds a
+    /// pointer into it, and struct fields don't guarantee `ring` outlives
+    /// it on drop -- wait out any outstanding prefetch explicitly first,
+    /// e.g. a `--count`-limited copy that stops before reaching EOF.
+    fn drop(&mut self) {
+        if self.prefetch.take().is_some() {
+            let _ = self.wait_outstanding();
+        }
+    }
+}
@@ -0,0 +1,133 @@
+//! Structured `--joblog` records. A dedicated writer task receives the
+//! record over an mpsc channel and appends it to the log file, so that
+//! file I/O never blocks the copy itself.
+
+use std::fs::OpenOptions;
+use std::io;
+use std::io::Write;
+use std::sync::mpsc::{channel, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Output format selected with `--joblog-format`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JobLogFormat {
+    Tsv,
+    Json,
+}
+
+impl JobLogFormat {
+    pub fn parse(name: &str) -> io::Result<Self> {
+        match name {
+            "tsv" => Ok(JobLogFormat::Tsv),
+            "json" => Ok(JobLogFormat::Json),
+            other => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("unknown joblog format '{}'", other),
+            )),
+        }
+    }
+}
+
+/// One completed (or failed) run, ready to be appended to the joblog.
+pub struct JobRecord {
+    pub start: SystemTime,
+    pub input: String,
+    pub output: String,
+    pub block_size: usize,
+    pub bytes_written: usize,
+    pub elapsed: Duration,
+    pub integrity: Option<String>,
+    pub error: Option<String>,
+}
+
+impl JobRecord {
+    fn avg_mib_s(&self) -> f32 {
+        let secs = self.elapsed.as_secs_f32().max(f32::EPSILON);
+        (self.bytes_written as f32 / secs) / 1_000_000.0
+    }
+
+    fn start_unix_secs(&self) -> u64 {
+        self.start
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs()
+    }
+
+    fn format(&self, format: JobLogFormat) -> String {
+        let status = if self.error.is_some() { "error" } else { "ok" };
+        match format {
+            JobLogFormat::Tsv => format!(
+                "{}\t{}\t{}\t{}\t{}\t{:.3}\t{:.1}\t{}\t{}\t{}\n",
+                self.start_unix_secs(),
+                self.input,
+                self.output,
+                self.block_size,
+                self.bytes_written,
+                self.elapsed.as_secs_f32(),
+                self.avg_mib_s(),
+                self.integrity.as_deref().unwrap_or("-"),
+                status,
+                self.error.as_deref().unwrap_or("-"),
+            ),
+            JobLogFormat::Json => format!(
+                "{{\"start\":{},\"input\":{},\"output\":{},\"block_size\":{},\"bytes_written\":{},\"elapsed_secs\":{:.3},\"avg_mib_s\":{:.1},\"integrity\":{},\"status\":\"{}\",\"error\":{}}}\n",
+                self.start_unix_secs(),
+                json_string(&self.input),
+                json_string(&self.output),
+                self.block_size,
+                self.bytes_written,
+                self.elapsed.as_secs_f32(),
+                self.avg_mib_s(),
+                json_opt(&self.integrity),
+                status,
+                json_opt(&self.error),
+            ),
+        }
+    }
+}
+
+fn json_opt(value: &Option<String>) -> String {
+    match value {
+        Some(s) => json_string(s),
+        None => "null".to_string(),
+    }
+}
+
+/// Quote and escape a string as a JSON string literal. `Debug`'s `{:?}`
+/// looks similar but isn't valid JSON: it renders control characters as
+/// variable-width escapes instead of JSON's fixed 4-hex-digit form, which
+/// would make a path or error message containing one fail to parse.
+fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// Spawn the joblog writer task. Send exactly one [`JobRecord`] to the
+/// returned sender, then join the returned handle to make sure it has been
+/// appended to disk before the process exits.
+pub fn spawn(path: String, format: JobLogFormat) -> (Sender<JobRecord>, JoinHandle<()>) {
+    let (tx, rx) = channel::<JobRecord>();
+    let handle = thread::spawn(move || {
+        if let Ok(record) = rx.recv() {
+            let line = record.format(format);
+            if let Ok(mut file) = OpenOptions::new().create(true).append(true).open(&path) {
+                let _ = file.write_all(line.as_bytes());
+            }
+        }
+    });
+    (tx, handle)
+}
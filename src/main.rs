@@ -1,16 +1,31 @@
 use argh::FromArgs;
 
+mod integrity;
+mod io_backend;
+mod joblog;
+mod mmap_copy;
+mod walk;
+mod writer;
+
+use integrity::ChecksumAlgo;
+
 #[derive(FromArgs)]
 /// dd writer goes drrrrrrrr
 pub struct AppArgs {
-    /// the file to read
+    /// the file to read; may be given more than once, and (with
+    /// --recursive) may be a directory
     #[argh(option)]
-    pub input: String,
+    pub input: Vec<String>,
 
-    /// the file to write
+    /// the file (or, with --recursive, the destination directory) to write
     #[argh(option)]
     pub output: String,
 
+    /// walk directory inputs and copy their contents, recreating the
+    /// directory structure under --output and preserving symlinks
+    #[argh(switch)]
+    pub recursive: bool,
+
     /// set the blocksize, default is 1MiB
     #[argh(option)]
     pub blocksize: Option<usize>,
@@ -22,13 +37,54 @@ pub struct AppArgs {
     /// show the progress of the OS sync operation, might give invalid numbers
     #[argh(switch)]
     pub sync_progress: bool,
+
+    /// hash the data as it is written and print an SSRI-style integrity
+    /// string when done (sha256/sha512/xxh3)
+    #[argh(option)]
+    pub checksum: Option<String>,
+
+    /// verify the written data against an expected integrity string (e.g.
+    /// sha512-<base64>), exits non-zero on mismatch
+    #[argh(option)]
+    pub verify: Option<String>,
+
+    /// inputs at or below this size use a single mmap'd copy instead of the
+    /// block-at-a-time loop, default is 1MiB (matching the default
+    /// blocksize), 0 disables mmap entirely
+    #[argh(option)]
+    pub mmap_max: Option<u64>,
+
+    /// skip this many blocks of the input before copying, like dd's skip=
+    #[argh(option)]
+    pub skip: Option<u64>,
+
+    /// seek this many blocks into the output before writing, like dd's seek=
+    #[argh(option)]
+    pub seek: Option<u64>,
+
+    /// byte-granular variant of --skip, takes precedence over it
+    #[argh(option)]
+    pub iseek_bytes: Option<u64>,
+
+    /// byte-granular variant of --seek, takes precedence over it
+    #[argh(option)]
+    pub oseek_bytes: Option<u64>,
+
+    /// append one structured record describing this run to this path
+    #[argh(option)]
+    pub joblog: Option<String>,
+
+    /// format for --joblog records, tsv or json, default is tsv
+    #[argh(option)]
+    pub joblog_format: Option<String>,
 }
 
-use async_std::fs::{File, OpenOptions};
-use async_std::io::prelude::*;
-use std::sync::mpsc::{channel, Sender};
+use std::path::PathBuf;
+use std::sync::mpsc::channel;
 use std::thread;
-use std::time::Instant;
+use std::time::{Instant, SystemTime};
+
+use writer::writer_thread;
 
 // const CLEAR_LINE: &str = "\r\x1b[K";
 
@@ -38,122 +94,460 @@ fn main() {
 
 async fn _main() -> Result<(), std::io::Error> {
     let args: AppArgs = argh::from_env();
+
+    if args.input.is_empty() {
+        eprintln!("At least one --input is required");
+        std::process::exit(1);
+    }
+
+    // Like cp, a directory input is refused outright unless --recursive
+    // says it's wanted -- it doesn't just get walked implicitly.
+    if !args.recursive {
+        for input in &args.input {
+            if std::fs::metadata(input).map(|m| m.is_dir()).unwrap_or(false) {
+                eprintln!("{}: is a directory (use --recursive to copy it)", input);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    let is_tree_mode = args.recursive || args.input.len() > 1;
+
+    if is_tree_mode {
+        return run_tree(args).await;
+    }
+
     let block_size = args.blocksize.unwrap_or(1024 * 1024);
+    let input = args.input[0].clone();
+    let input_is_stdin = input == "-";
+    let output_is_stdout = args.output == "-";
 
+    // With stdin as the input there's no file to stat, so the total size
+    // (and with it, percentage progress) is simply unknown.
     let total = if let Some(count) = args.count {
-        count * block_size
+        Some(count * block_size)
+    } else if input_is_stdin {
+        None
     } else {
-        std::fs::metadata(&args.input)
-            .expect("Could not read input file")
-            .len() as usize
+        Some(
+            std::fs::metadata(&input)
+                .expect("Could not read input file")
+                .len() as usize,
+        )
     };
 
-    if let Ok(meta) = std::fs::metadata(&args.output) {
-        if meta.is_dir() {
-            println!("The output file is a directory. Aborting");
-            std::process::exit(0);
+    if !output_is_stdout {
+        if let Ok(meta) = std::fs::metadata(&args.output) {
+            if meta.is_dir() {
+                println!("The output file is a directory. Aborting");
+                std::process::exit(0);
+            }
         }
     }
 
-    let source = File::open(&args.input).await?;
+    let checksum = args
+        .checksum
+        .as_deref()
+        .map(ChecksumAlgo::parse)
+        .transpose()
+        .expect("Unknown --checksum algorithm");
+
+    let verify = args
+        .verify
+        .as_deref()
+        .map(integrity::parse_integrity)
+        .transpose()
+        .expect("Malformed --verify integrity string");
+
+    // --verify implies hashing with whatever algorithm it names, even if
+    // --checksum wasn't also given.
+    let checksum = checksum.or_else(|| verify.as_ref().map(|(algo, _)| *algo));
+
+    let skip_bytes = args
+        .iseek_bytes
+        .unwrap_or_else(|| args.skip.unwrap_or(0) * block_size as u64);
+    let seek_bytes = args
+        .oseek_bytes
+        .unwrap_or_else(|| args.seek.unwrap_or(0) * block_size as u64);
+
+    // The skipped region of the input is never read, so it never counts
+    // towards the total we're copying. A --count total is already just the
+    // blocks read after the skip, so it's left as it is.
+    let total = if args.count.is_some() {
+        total
+    } else {
+        total.map(|total| total.saturating_sub(skip_bytes as usize))
+    };
+
+    let mmap_max = args.mmap_max.unwrap_or(1024 * 1024);
+    let use_mmap = !input_is_stdin
+        && !output_is_stdout
+        && skip_bytes == 0
+        && seek_bytes == 0
+        && mmap_max != 0
+        // mmap_copy always copies the whole file; --count asks for only
+        // the first N blocks, which it has no way to honor.
+        && args.count.is_none()
+        && std::fs::metadata(&input)
+            .expect("Could not read input file")
+            .len()
+            <= mmap_max;
 
-    let target = OpenOptions::new()
-        .write(true)
-        .create(true)
-        .open(&args.output)
-        .await
-        .expect("Could not open output file");
+    let joblog_format = args
+        .joblog_format
+        .as_deref()
+        .map(joblog::JobLogFormat::parse)
+        .transpose()
+        .expect("Unknown --joblog-format")
+        .unwrap_or(joblog::JobLogFormat::Tsv);
+    let joblog_writer = args.joblog.clone().map(|path| joblog::spawn(path, joblog_format));
+    let joblog_input = input.clone();
+    let joblog_output = args.output.clone();
 
     let (tx, rx) = channel();
 
+    let run_start = SystemTime::now();
     let t = Instant::now();
-    let handle = thread::spawn(move || {
-        async_std::task::block_on(writer_thread(tx, block_size, args.count, source, target))
-            .unwrap()
-    });
+    let handle = if use_mmap {
+        let input = input.clone();
+        let output = args.output.clone();
+        thread::spawn(move || mmap_copy::mmap_copy(tx, &input, &output, checksum))
+    } else {
+        let input = input.clone();
+        let output = args.output.clone();
+
+        thread::spawn(move || {
+            async_std::task::block_on(writer_thread(
+                tx,
+                block_size,
+                args.count,
+                input,
+                output,
+                checksum,
+                skip_bytes,
+                seek_bytes,
+            ))
+        })
+    };
     println!("Writing file to OS buffer");
 
+    // The OS-sync-progress phase below estimates how much of the write is
+    // still sitting in dirty pages via /proc/meminfo, which only makes
+    // sense when we know the total size and are syncing a real file.
+    let can_show_sync_progress = total.is_some() && !output_is_stdout;
+
     let mut last_written = 0;
     for written in rx.iter() {
+        if !can_show_sync_progress && written == 0 {
+            // This is the post-sync completion signal; there's no
+            // meminfo-based estimate to show, so stop here.
+            break;
+        }
+
         std::thread::sleep(std::time::Duration::from_millis(100));
-        println!(
-            "Progress {}% ({}MiB of {}MiB, {:.1}MiB/s)",
-            ((written as f32 / total as f32) * 100.0).floor(),
-            written / 1_000_000,
-            total / 1_000_000,
-            (written as f32 - last_written as f32) / 1_000_000.0
-        );
+        print_progress(written, total, last_written);
         last_written = written;
 
-        if written == total {
+        if can_show_sync_progress && total == Some(written) {
             println!("Syncing filesystem");
             break;
         };
     }
 
+    if can_show_sync_progress {
+        let total = total.unwrap();
+        loop {
+            match rx.try_recv() {
+                Ok(0) => break,
+                Ok(_) => {}
+                Err(std::sync::mpsc::TryRecvError::Disconnected) => break,
+                Err(std::sync::mpsc::TryRecvError::Empty) => {}
+            }
+
+            let meminfo = async_std::fs::read_to_string("/proc/meminfo").await?;
+            let line = meminfo
+                .split('\n')
+                .filter(|s| s.contains("Dirty"))
+                .nth(0)
+                .unwrap();
+            let dirty = line.split(":").nth(1).unwrap().replace("kB", "");
+            let dirty: usize = dirty.trim().parse().unwrap();
+            let progress = total - dirty * 1000;
+
+            print_progress(progress, Some(total), last_written);
+            last_written = progress;
+            std::thread::sleep(std::time::Duration::from_millis(500));
+        }
+    }
+
+    let elapsed = t.elapsed();
+    let result = handle.join().unwrap();
+
+    let mut exit_code = 0;
+    let mut verify_error = None;
+    match &result {
+        Ok(outcome) => {
+            println!(
+                "Finished in {:?}, {:.1}MiB/s",
+                elapsed,
+                (outcome.written as f32 / elapsed.as_secs_f32().max(f32::EPSILON)) / 1_000_000.0
+            );
+            if let Some(msg) = report_integrity(&outcome.integrity, &verify) {
+                exit_code = 1;
+                verify_error = Some(msg);
+            }
+        }
+        Err(e) => {
+            eprintln!("Copy failed after {:?}: {}", elapsed, e);
+            exit_code = 1;
+        }
+    }
+
+    if let Some((joblog_tx, joblog_handle)) = joblog_writer {
+        // A --verify mismatch still leaves `result` as `Ok` (the copy
+        // itself succeeded), so without this the joblog would record a
+        // run that exits non-zero as "status":"ok".
+        let (bytes_written, integrity, error) = match &result {
+            Ok(outcome) => (outcome.written, outcome.integrity.clone(), verify_error),
+            Err(e) => (last_written, None, Some(e.to_string())),
+        };
+        let _ = joblog_tx.send(joblog::JobRecord {
+            start: run_start,
+            input: joblog_input,
+            output: joblog_output,
+            block_size,
+            bytes_written,
+            elapsed,
+            integrity,
+            error,
+        });
+        drop(joblog_tx);
+        let _ = joblog_handle.join();
+    }
+
+    if exit_code != 0 {
+        std::process::exit(exit_code);
+    }
+
+    Ok(())
+}
+
+/// The `--recursive` / multi-`--input` / directory-input path. Plans the
+/// whole tree up front, recreates symlinks, then copies every regular file
+/// through the same [`writer_thread`] the single-file path uses, reporting
+/// a combined byte count across all of them.
+async fn run_tree(args: AppArgs) -> Result<(), std::io::Error> {
+    // A single expected digest doesn't have a sensible meaning once a copy
+    // can span an arbitrary number of files, so refuse the combination
+    // outright rather than silently checking it against nothing.
+    if args.verify.is_some() {
+        eprintln!("--verify is not supported with --recursive or multiple/directory --input");
+        std::process::exit(1);
+    }
+
+    let block_size = args.blocksize.unwrap_or(1024 * 1024);
+    let output_root = PathBuf::from(&args.output);
+
+    async_std::fs::create_dir_all(&output_root).await?;
+
+    let (files, symlinks, dirs) = walk::plan(&args.input, &output_root).await?;
+    walk::create_directories(&dirs).await?;
+    walk::recreate_symlinks(&symlinks).await?;
+
+    let file_count = files.len();
+    let total: usize = files
+        .iter()
+        .filter_map(|job| std::fs::metadata(&job.source).ok())
+        .map(|meta| meta.len() as usize)
+        .sum();
+
+    let checksum = args
+        .checksum
+        .as_deref()
+        .map(ChecksumAlgo::parse)
+        .transpose()
+        .expect("Unknown --checksum algorithm");
+
+    let joblog_format = args
+        .joblog_format
+        .as_deref()
+        .map(joblog::JobLogFormat::parse)
+        .transpose()
+        .expect("Unknown --joblog-format")
+        .unwrap_or(joblog::JobLogFormat::Tsv);
+    let joblog_writer = args.joblog.clone().map(|path| joblog::spawn(path, joblog_format));
+    let joblog_input = args.input.join(",");
+    let joblog_output = args.output.clone();
+
+    let (tx, rx) = channel::<(PathBuf, usize)>();
+
+    let run_start = SystemTime::now();
+    let t = Instant::now();
+    let handle =
+        thread::spawn(move || async_std::task::block_on(walk::copy_files(&files, block_size, checksum, tx)));
+
+    println!("Copying {} files", file_count);
+
+    // Mirrors _main's OS-sync-progress phase: once every file has reported
+    // its full byte count, each of them is about to (or already did) call
+    // sync_data internally, so there's still a dirty-page flush worth
+    // estimating before the whole tree is actually durable.
+    let can_show_sync_progress = total > 0;
+
+    let mut per_file: std::collections::HashMap<PathBuf, usize> = std::collections::HashMap::new();
     let mut last_written = 0;
-    loop {
-        if let Ok(signal) = rx.try_recv() {
-            if signal == 0 {
-                let elapsed = t.elapsed();
-                println!(
-                    "Finished in {:?}, {:.1}MiB/s",
-                    elapsed,
-                    (total as f32 / elapsed.as_secs() as f32) / 1_000_000.0
-                );
-
-                return Ok(());
+    for (path, written) in rx.iter() {
+        per_file.insert(path, written);
+        let combined: usize = per_file.values().sum();
+
+        std::thread::sleep(std::time::Duration::from_millis(100));
+        print_progress(combined, if total > 0 { Some(total) } else { None }, last_written);
+        last_written = combined;
+
+        if can_show_sync_progress && combined >= total {
+            println!("Syncing filesystem");
+            break;
+        }
+    }
+
+    if can_show_sync_progress {
+        loop {
+            match rx.try_recv() {
+                Ok((path, written)) => {
+                    per_file.insert(path, written);
+                }
+                Err(std::sync::mpsc::TryRecvError::Disconnected) => break,
+                Err(std::sync::mpsc::TryRecvError::Empty) => {}
             }
+
+            let meminfo = async_std::fs::read_to_string("/proc/meminfo").await?;
+            let line = meminfo
+                .split('\n')
+                .filter(|s| s.contains("Dirty"))
+                .nth(0)
+                .unwrap();
+            let dirty = line.split(":").nth(1).unwrap().replace("kB", "");
+            let dirty: usize = dirty.trim().parse().unwrap();
+            let progress = total.saturating_sub(dirty * 1000);
+
+            print_progress(progress, Some(total), last_written);
+            last_written = progress;
+            std::thread::sleep(std::time::Duration::from_millis(500));
         }
+    }
+
+    let elapsed = t.elapsed();
+    let result = handle.join().unwrap();
+
+    let mut exit_code = 0;
+    match &result {
+        Ok(outcome) => {
+            println!(
+                "Finished {} files in {:?}, {:.1}MiB/s",
+                outcome.files_copied,
+                elapsed,
+                (outcome.bytes_written as f32 / elapsed.as_secs_f32().max(f32::EPSILON)) / 1_000_000.0
+            );
+            for (path, integrity) in &outcome.integrity {
+                println!("Integrity: {}: {}", path.display(), integrity);
+            }
+        }
+        Err(e) => {
+            eprintln!("Copy failed after {:?}: {}", elapsed, e);
+            exit_code = 1;
+        }
+    }
+
+    if let Some((joblog_tx, joblog_handle)) = joblog_writer {
+        let (bytes_written, integrity, error) = match &result {
+            Ok(outcome) => (
+                outcome.bytes_written,
+                // One digest per file copied, not just the first -- a
+                // multi-file --recursive --checksum run needs all of them
+                // to be verifiable from the joblog alone.
+                if outcome.integrity.is_empty() {
+                    None
+                } else {
+                    Some(
+                        outcome
+                            .integrity
+                            .iter()
+                            .map(|(path, digest)| format!("{}={}", path.display(), digest))
+                            .collect::<Vec<_>>()
+                            .join(","),
+                    )
+                },
+                None,
+            ),
+            Err(e) => (last_written, None, Some(e.to_string())),
+        };
+        let _ = joblog_tx.send(joblog::JobRecord {
+            start: run_start,
+            input: joblog_input,
+            output: joblog_output,
+            block_size,
+            bytes_written,
+            elapsed,
+            integrity,
+            error,
+        });
+        drop(joblog_tx);
+        let _ = joblog_handle.join();
+    }
 
-        let meminfo = async_std::fs::read_to_string("/proc/meminfo").await?;
-        let line = meminfo
-            .split('\n')
-            .filter(|s| s.contains("Dirty"))
-            .nth(0)
-            .unwrap();
-        let dirty = line.split(":").nth(1).unwrap().replace("kB", "");
-        let dirty: usize = dirty.trim().parse().unwrap();
-        let progress = total - dirty * 1000;
-
-        println!(
+    if exit_code != 0 {
+        std::process::exit(exit_code);
+    }
+
+    Ok(())
+}
+
+/// Print one progress line. Falls back to bytes/throughput without a
+/// percentage when `total` is unknown (piping from stdin).
+fn print_progress(written: usize, total: Option<usize>, last_written: usize) {
+    let throughput = (written as f32 - last_written as f32) / 1_000_000.0;
+    match total {
+        Some(total) => println!(
             "Progress {}% ({}MiB of {}MiB, {:.1}MiB/s)",
-            ((progress as f32 / total as f32) * 100.0).floor(),
-            progress / 1_000_000,
+            ((written as f32 / total as f32) * 100.0).floor(),
+            written / 1_000_000,
             total / 1_000_000,
-            (progress as f32 - last_written as f32) / 1_000_000.0
-        );
-        last_written = progress;
-        std::thread::sleep(std::time::Duration::from_millis(500));
+            throughput
+        ),
+        None => println!(
+            "Progress ({}MiB written, {:.1}MiB/s)",
+            written / 1_000_000,
+            throughput
+        ),
     }
 }
 
-async fn writer_thread(
-    tx: Sender<usize>,
-    block_size: usize,
-    count: Option<usize>,
-    mut source: File,
-    mut target: File,
-) -> Result<(), std::io::Error> {
-    let mut count = count.unwrap_or(usize::max_value());
-    let mut written = 0;
-    let mut buf = vec![0; block_size];
-    let mut last_print = Instant::now();
-    let mut read = 1;
-    while read != 0 && count > 0 {
-        read = source.read(&mut buf).await?;
-        target.write(&mut buf).await?;
-
-        written += read;
-        count -= 1;
-        if last_print.elapsed().as_millis() > 500 {
-            tx.send(written).unwrap();
-            last_print = Instant::now();
+/// Print the computed integrity string (if any) and, when `--verify` named
+/// an expected one, compare against it. Returns the mismatch message on
+/// failure so the caller can both exit non-zero and record the same reason
+/// in the joblog, instead of the joblog silently saying "ok".
+fn report_integrity(integrity: &Option<String>, verify: &Option<(ChecksumAlgo, String)>) -> Option<String> {
+    if let Some(expected) = verify {
+        match integrity {
+            Some(actual) if actual == &expected.1 => {
+                println!("Integrity OK: {}", actual);
+                None
+            }
+            Some(actual) => {
+                let msg = format!("Integrity mismatch: expected {}, got {}", expected.1, actual);
+                eprintln!("{}", msg);
+                Some(msg)
+            }
+            None => {
+                let msg = "Integrity mismatch: no checksum was computed".to_string();
+                eprintln!("{}", msg);
+                Some(msg)
+            }
         }
+    } else {
+        if let Some(integrity) = integrity {
+            println!("Integrity: {}", integrity);
+        }
+        None
     }
-    tx.send(written).unwrap();
-    target.sync_data().await.unwrap_or_default();
-    tx.send(0).unwrap();
-
-    Ok(())
 }
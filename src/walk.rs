@@ -0,0 +1,190 @@
+//! Recursive / multi-source copy used when `--recursive` is given or more
+//! than one `--input` is given; a directory input is only accepted
+//! together with `--recursive`. Each directory input is merged into `--output` the way
+//! `rsync -a input/ output/` would (its own top-level name is not
+//! recreated), so several `--input` directories can all land in the same
+//! destination tree. Symlinks are recreated as-is rather than followed,
+//! and anything that isn't a regular file, directory, or symlink (sockets,
+//! fifos, devices, ...) is skipped.
+
+use crate::integrity::ChecksumAlgo;
+use crate::writer::writer_thread;
+use async_std::fs;
+use async_std::prelude::*;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Sender};
+
+/// A single regular file or symlink discovered by [`plan`], paired with
+/// where it should land under `--output`.
+pub struct FileJob {
+    pub source: PathBuf,
+    pub dest: PathBuf,
+}
+
+/// What the whole tree copy produced.
+pub struct TreeOutcome {
+    pub files_copied: usize,
+    pub bytes_written: usize,
+    /// Per-file integrity strings, only populated when `--checksum` was
+    /// given.
+    pub integrity: Vec<(PathBuf, String)>,
+}
+
+/// Walk every input, splitting what it finds into regular files to copy,
+/// symlinks to recreate, and directories to recreate (including ones that
+/// turn out to be empty), with destinations already resolved under
+/// `output_root`.
+pub async fn plan(
+    inputs: &[String],
+    output_root: &Path,
+) -> std::io::Result<(Vec<FileJob>, Vec<FileJob>, Vec<PathBuf>)> {
+    let mut files = Vec::new();
+    let mut symlinks = Vec::new();
+    let mut dirs = Vec::new();
+
+    for input in inputs {
+        let input_root = PathBuf::from(input);
+        let meta = fs::symlink_metadata(&input_root).await?;
+        let file_name = input_root
+            .file_name()
+            .map(PathBuf::from)
+            .unwrap_or_else(|| input_root.clone());
+
+        if meta.file_type().is_symlink() {
+            symlinks.push(FileJob {
+                source: input_root,
+                dest: output_root.join(file_name),
+            });
+            continue;
+        }
+
+        if meta.is_file() {
+            files.push(FileJob {
+                source: input_root,
+                dest: output_root.join(file_name),
+            });
+            continue;
+        }
+
+        // A directory: walk it and merge its contents directly into
+        // output_root, so its own name isn't recreated one level deeper.
+        let mut stack = vec![input_root.clone()];
+        while let Some(dir) = stack.pop() {
+            let mut entries = fs::read_dir(&dir).await?;
+            while let Some(entry) = entries.next().await {
+                let entry = entry?;
+                let path = PathBuf::from(entry.path());
+                let relative = path
+                    .strip_prefix(&input_root)
+                    .expect("walked entry must live under its own input root")
+                    .to_path_buf();
+                let dest = output_root.join(&relative);
+                let entry_meta = fs::symlink_metadata(&path).await?;
+
+                if entry_meta.file_type().is_symlink() {
+                    symlinks.push(FileJob { source: path, dest });
+                } else if entry_meta.is_dir() {
+                    dirs.push(dest);
+                    stack.push(path);
+                } else if entry_meta.is_file() {
+                    files.push(FileJob { source: path, dest });
+                }
+                // Special files (sockets, fifos, devices, ...) are skipped.
+            }
+        }
+    }
+
+    Ok((files, symlinks, dirs))
+}
+
+/// Recreate every directory from `plan`, including ones with nothing left
+/// in them once special files are skipped. `create_dir_all` also covers
+/// the directories `copy_files`/`recreate_symlinks` would otherwise create
+/// on demand, so this just has to run first.
+pub async fn create_directories(dirs: &[PathBuf]) -> std::io::Result<()> {
+    for dir in dirs {
+        fs::create_dir_all(dir).await?;
+    }
+    Ok(())
+}
+
+/// Recreate every symlink from `plan`, overwriting whatever (if anything)
+/// is already at the destination.
+pub async fn recreate_symlinks(symlinks: &[FileJob]) -> std::io::Result<()> {
+    for symlink in symlinks {
+        if let Some(parent) = symlink.dest.parent() {
+            fs::create_dir_all(parent).await?;
+        }
+        let target = fs::read_link(&symlink.source).await?;
+        let _ = fs::remove_file(&symlink.dest).await;
+
+        #[cfg(unix)]
+        async_std::os::unix::fs::symlink(&target, &symlink.dest).await?;
+        #[cfg(not(unix))]
+        let _ = target;
+    }
+    Ok(())
+}
+
+/// Copy every planned file through the same [`writer_thread`] used for a
+/// single file, reporting each file's running byte count into the shared
+/// `progress` channel so the caller can aggregate a global total.
+pub async fn copy_files(
+    files: &[FileJob],
+    block_size: usize,
+    checksum: Option<ChecksumAlgo>,
+    progress: Sender<(PathBuf, usize)>,
+) -> std::io::Result<TreeOutcome> {
+    let mut bytes_written = 0;
+    let mut integrity = Vec::new();
+
+    for job in files {
+        if let Some(parent) = job.dest.parent() {
+            fs::create_dir_all(parent).await?;
+        }
+
+        let source = job.source.to_string_lossy().into_owned();
+        let dest = job.dest.to_string_lossy().into_owned();
+
+        // writer_thread only knows how to report a running byte count on
+        // its own Sender<usize>; tag each update with this file's path
+        // before forwarding it into the shared aggregator. Its final
+        // `tx.send(0)` is just an end-of-copy sentinel for the single-file
+        // path, not a real byte count, so it's dropped here rather than
+        // forwarded -- otherwise the aggregator would see this file's
+        // contribution collapse back to 0 the instant it finishes.
+        let (file_tx, file_rx) = channel::<usize>();
+        let path_for_report = job.source.clone();
+        let progress_for_file = progress.clone();
+        let forward = std::thread::spawn(move || {
+            for written in file_rx.iter() {
+                if written == 0 {
+                    continue;
+                }
+                if progress_for_file
+                    .send((path_for_report.clone(), written))
+                    .is_err()
+                {
+                    break;
+                }
+            }
+        });
+
+        let outcome = writer_thread(
+            file_tx, block_size, None, source, dest, checksum, 0, 0,
+        )
+        .await?;
+        forward.join().unwrap();
+
+        bytes_written += outcome.written;
+        if let Some(digest) = outcome.integrity {
+            integrity.push((job.source.clone(), digest));
+        }
+    }
+
+    Ok(TreeOutcome {
+        files_copied: files.len(),
+        bytes_written,
+        integrity,
+    })
+}
@@ -0,0 +1,88 @@
+use crate::integrity::{ChecksumAlgo, RollingHasher};
+use crate::io_backend::File;
+use std::sync::mpsc::Sender;
+use std::time::Instant;
+
+/// What the writer thread hands back once the copy (and sync) is done.
+pub struct WriterOutcome {
+    pub written: usize,
+    pub integrity: Option<String>,
+}
+
+pub async fn writer_thread(
+    tx: Sender<usize>,
+    block_size: usize,
+    count: Option<usize>,
+    input: String,
+    output: String,
+    checksum: Option<ChecksumAlgo>,
+    skip_bytes: u64,
+    seek_bytes: u64,
+) -> Result<WriterOutcome, std::io::Error> {
+    let mut source = File::open(&input).await?;
+    let mut target = File::create(&output, seek_bytes == 0).await?;
+
+    source.seek(skip_bytes).await?;
+    target.seek(seek_bytes).await?;
+
+    let mut count = count.unwrap_or(usize::max_value());
+    let mut written = 0;
+    let mut buf = vec![0; block_size];
+    let mut last_print = Instant::now();
+    let mut read = 1;
+    let mut hasher = checksum.map(RollingHasher::new);
+
+    while read != 0 && count > 0 {
+        read = read_full(&mut source, &mut buf).await?;
+        write_all(&mut target, &buf[..read]).await?;
+
+        if let Some(hasher) = &mut hasher {
+            hasher.update(&buf[..read]);
+        }
+
+        written += read;
+        count -= 1;
+        if last_print.elapsed().as_millis() > 500 {
+            tx.send(written).unwrap();
+            last_print = Instant::now();
+        }
+    }
+    tx.send(written).unwrap();
+    // A failed sync means the bytes were never confirmed durable, so it
+    // must fail the copy rather than let a digest get computed and
+    // reported as if they were.
+    target.sync_data().await?;
+
+    // Finalizing after sync_data means a partial flush never reports a
+    // "good" hash.
+    let integrity = hasher.map(|h| h.finish());
+
+    tx.send(0).unwrap();
+
+    Ok(WriterOutcome { written, integrity })
+}
+
+/// Like `Read::read`, but keeps reading until `buf` is full or the source
+/// is at EOF, so a short read never short-changes the hash or the write.
+async fn read_full(source: &mut File, buf: &mut [u8]) -> Result<usize, std::io::Error> {
+    let mut read = 0;
+    while read < buf.len() {
+        let n = source.read(&mut buf[read..]).await?;
+        if n == 0 {
+            break;
+        }
+        read += n;
+    }
+    Ok(read)
+}
+
+/// Like `Write::write`, but keeps writing until all of `buf` has been
+/// accepted, so a short write never silently drops bytes.
+async fn write_all(target: &mut File, buf: &[u8]) -> Result<(), std::io::Error> {
+    let mut written = 0;
+    while written < buf.len() {
+        let n = target.write(&buf[written..]).await?;
+        written += n;
+    }
+    Ok(())
+}